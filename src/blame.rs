@@ -0,0 +1,216 @@
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use thiserror::Error;
+
+use crate::Args;
+
+static GIT_BLAME_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\^?([0-9a-f]+) (?:([^(^)]+)\s+)?(\(.* \d+\)) (.*)").unwrap());
+
+/// One line's attribution, as reported by a `Blame` implementation.
+///
+/// `commit_id` may be a full object id (`gix_library`) or an abbreviated
+/// prefix (`git_subprocess`'s scraped output), so callers compare them
+/// with `starts_with` rather than equality.
+#[derive(Debug, Clone)]
+pub struct BlameLine {
+    pub commit_id: String,
+    pub line_number: usize,
+    pub content: String,
+}
+
+#[derive(Debug, Error)]
+pub enum BlameError {
+    #[error("failed to run blame: {0}")]
+    Io(#[source] std::io::Error),
+
+    #[error("blame exited with a non-zero status:\n{0}")]
+    NonZeroExit(String),
+
+    #[error("blame line did not match the expected pattern: {0:?}")]
+    LineDidNotMatchPattern(String),
+
+    #[error("gix blame failed: {0}")]
+    Gix(String),
+
+    #[error(
+        "gix_library does not support extra blame arguments ({0:?}); pass them to \
+         git_subprocess instead, or drop --args when comparing against gix"
+    )]
+    UnsupportedArgs(String),
+}
+
+/// Obtains a structured blame for a single file.
+///
+/// Implementors are free to shell out to an executable or talk to a
+/// library directly; `compare_two_blames` only ever sees the resulting
+/// `Vec<BlameLine>`. `blame_for_executable` picks which implementation
+/// produces the blame for a given executable.
+pub trait Blame {
+    fn blame(
+        &self,
+        args: &Args,
+        extra_args: &str,
+        filename: &str,
+    ) -> Result<Vec<BlameLine>, BlameError>;
+}
+
+/// Runs `<executable> blame` as a subprocess and parses its output with
+/// `GIT_BLAME_RE`. This is the baseline implementation, used for `git`.
+pub struct GitSubprocess {
+    pub executable: PathBuf,
+}
+
+impl Blame for GitSubprocess {
+    fn blame(
+        &self,
+        args: &Args,
+        extra_args: &str,
+        filename: &str,
+    ) -> Result<Vec<BlameLine>, BlameError> {
+        // Invoke the executable directly with an argv vector rather than
+        // building a shell command string and running it through `bash
+        // -c`. This keeps us working on platforms without bash and
+        // sidesteps shell quoting for filenames with spaces or
+        // metacharacters.
+        let output = Command::new(&self.executable)
+            .env("GIT_DIR", args.git_dir())
+            .env("GIT_WORK_TREE", args.git_work_tree.clone())
+            .arg("blame")
+            .args(extra_args.split_whitespace())
+            .arg(filename)
+            .output()
+            .map_err(BlameError::Io)?;
+
+        if !output.status.success() {
+            return Err(BlameError::NonZeroExit(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+
+        output
+            .stdout
+            .lines()
+            .enumerate()
+            .map(|(line_number, line)| {
+                let line = line.map_err(BlameError::Io)?;
+                let captures = GIT_BLAME_RE
+                    .captures(&line)
+                    .ok_or_else(|| BlameError::LineDidNotMatchPattern(line.clone()))?;
+
+                Ok(BlameLine {
+                    commit_id: captures[1].to_string(),
+                    line_number,
+                    content: captures[4].to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Computes blame in-process via the `gix`/`gitoxide` crates instead of
+/// spawning `gix blame` and scraping its output. This avoids one process
+/// spawn per file and yields full object ids rather than the prefixes
+/// `gix blame`'s text output prints.
+pub struct GixLibrary;
+
+impl Blame for GixLibrary {
+    fn blame(
+        &self,
+        args: &Args,
+        extra_args: &str,
+        filename: &str,
+    ) -> Result<Vec<BlameLine>, BlameError> {
+        // The library API has no equivalent of arbitrary `git blame` CLI
+        // flags (a revision, `-w`, `-C`, ...), so comparing it against a
+        // git_subprocess run made with `--args` would silently compare
+        // apples to oranges. Refuse instead of producing spurious
+        // mismatches.
+        if !extra_args.trim().is_empty() {
+            return Err(BlameError::UnsupportedArgs(extra_args.to_string()));
+        }
+
+        let repo =
+            gix::open(&args.git_work_tree).map_err(|err| BlameError::Gix(err.to_string()))?;
+
+        let head_id = repo
+            .head_id()
+            .map_err(|err| BlameError::Gix(err.to_string()))?;
+
+        let mut resource_cache = repo
+            .diff_resource_cache(gix::diff::blob::pipeline::Mode::ToGit, Default::default())
+            .map_err(|err| BlameError::Gix(err.to_string()))?;
+
+        let outcome = gix::blame::file(
+            &repo.objects,
+            head_id.detach(),
+            None,
+            &mut resource_cache,
+            filename.as_ref(),
+            gix::blame::Options::default(),
+        )
+        .map_err(|err| BlameError::Gix(err.to_string()))?;
+
+        // Read the blamed blob out of the object database at `head_id`
+        // rather than off disk: the working tree can have uncommitted
+        // changes or simply be checked out to a different revision, and
+        // either would desync `content` from the commit `entry` actually
+        // attributes the line to.
+        let commit = head_id
+            .object()
+            .map_err(|err| BlameError::Gix(err.to_string()))?;
+        let tree = commit
+            .peel_to_tree()
+            .map_err(|err| BlameError::Gix(err.to_string()))?;
+        let blob = tree
+            .lookup_entry_by_path(filename)
+            .map_err(|err| BlameError::Gix(err.to_string()))?
+            .ok_or_else(|| BlameError::Gix(format!("{filename:?} not found at HEAD")))?
+            .object()
+            .map_err(|err| BlameError::Gix(err.to_string()))?;
+        let contents = String::from_utf8_lossy(&blob.data).into_owned();
+        let lines: Vec<&str> = contents.lines().collect();
+
+        let mut blame_lines: Vec<BlameLine> = outcome
+            .entries
+            .iter()
+            .flat_map(|entry| {
+                entry.range_in_blamed_file().map(|line_number| {
+                    let line_number = line_number as usize;
+
+                    BlameLine {
+                        commit_id: entry.commit_id.to_string(),
+                        line_number,
+                        content: lines.get(line_number).copied().unwrap_or("").to_string(),
+                    }
+                })
+            })
+            .collect();
+
+        // `compare_two_blames` zips baseline and comparison lines up
+        // positionally, which only works if both are in ascending line
+        // order. `outcome.entries` is not documented to be sorted, so
+        // sort explicitly rather than relying on gitoxide's iteration
+        // order.
+        blame_lines.sort_by_key(|line| line.line_number);
+
+        Ok(blame_lines)
+    }
+}
+
+/// Picks the `Blame` implementation for `executable`, preferring the
+/// in-process `gix_library` path when it resolves to `gix` itself and
+/// falling back to `git_subprocess` otherwise.
+pub fn blame_for_executable(executable: &Path) -> Box<dyn Blame> {
+    if executable.ends_with("gix") {
+        Box::new(GixLibrary)
+    } else {
+        Box::new(GitSubprocess {
+            executable: executable.to_path_buf(),
+        })
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::git::{git, GitError};
+
+/// Runs `git whatchanged` for `revision_range` and parses its
+/// `:<mode> <mode> <sha> <sha> <flag>\t<filename>` change lines into a
+/// map of path to the most recent unix timestamp it was changed at.
+///
+/// This lets a run be restricted to files touched in a commit range
+/// rather than every tracked file, by intersecting the result with the
+/// `ls-files` output.
+pub fn changed_files_since(
+    cwd: &Path,
+    git_dir: &Path,
+    revision_range: &str,
+) -> Result<HashMap<String, i64>, GitError> {
+    let output = git(
+        &[
+            "whatchanged",
+            "--pretty=format:%ad",
+            "--date=unix",
+            revision_range,
+        ],
+        &[("GIT_DIR", &git_dir.to_string_lossy())],
+        cwd,
+    )?;
+
+    let mut most_recent_change: HashMap<String, i64> = HashMap::new();
+    let mut current_timestamp: Option<i64> = None;
+
+    for line in output.lines() {
+        if let Ok(timestamp) = line.trim().parse::<i64>() {
+            current_timestamp = Some(timestamp);
+            continue;
+        }
+
+        // A plain change line is `:<mode> <mode> <sha> <sha> <flag>\t<path>`,
+        // but a rename/copy line (flag `R*`/`C*`) has a second tab and a
+        // second path, `...\t<old path>\t<new path>`. Take the last field
+        // so renamed/copied files are keyed by their current path, which is
+        // what `ls-files` reports.
+        let Some(filename) = line
+            .strip_prefix(':')
+            .and_then(|rest| rest.split('\t').last())
+        else {
+            continue;
+        };
+
+        let Some(timestamp) = current_timestamp else {
+            continue;
+        };
+
+        most_recent_change
+            .entry(filename.to_string())
+            .and_modify(|existing| *existing = (*existing).max(timestamp))
+            .or_insert(timestamp);
+    }
+
+    Ok(most_recent_change)
+}
@@ -0,0 +1,131 @@
+use std::path::Path;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FilterError {
+    #[error("invalid glob pattern {0:?}: {1}")]
+    InvalidGlob(String, #[source] globset::Error),
+
+    #[error("invalid ignore file {0:?}: {1}")]
+    InvalidIgnoreFile(std::path::PathBuf, #[source] ignore::Error),
+}
+
+/// How many paths a [`FileFilter`] dropped, broken down by the filter
+/// that dropped them.
+#[derive(Debug, Default)]
+pub struct FilterCounts {
+    pub excluded_by_include: usize,
+    pub excluded_by_exclude: usize,
+}
+
+/// Narrows the paths returned by `git ls-files` down to the ones a user
+/// actually wants to blame, via `--include`/`--exclude` globs and an
+/// optional `.gitignore`-style file of additional exclude patterns,
+/// matched with real gitignore semantics (negation, anchoring, etc.)
+/// rather than as plain globs.
+pub struct FileFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    ignore: Option<Gitignore>,
+}
+
+impl FileFilter {
+    pub fn new(
+        include: &[String],
+        exclude: &[String],
+        ignore_file: Option<&Path>,
+    ) -> Result<Self, FilterError> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(include)?)
+        };
+
+        let ignore = ignore_file.map(build_gitignore).transpose()?;
+
+        Ok(FileFilter {
+            include,
+            exclude: build_glob_set(exclude)?,
+            ignore,
+        })
+    }
+
+    /// Filters `paths`, returning the surviving paths alongside counts of
+    /// how many were dropped by each filter.
+    pub fn apply(&self, paths: Vec<String>) -> (Vec<String>, FilterCounts) {
+        let mut counts = FilterCounts::default();
+
+        let kept = paths
+            .into_iter()
+            .filter(|path| {
+                if !self.is_included(path) {
+                    counts.excluded_by_include += 1;
+                    return false;
+                }
+
+                if self.is_excluded(path) {
+                    counts.excluded_by_exclude += 1;
+                    return false;
+                }
+
+                true
+            })
+            .collect();
+
+        (kept, counts)
+    }
+
+    fn is_included(&self, path: &str) -> bool {
+        match &self.include {
+            Some(include) => include.is_match(path),
+            None => true,
+        }
+    }
+
+    fn is_excluded(&self, path: &str) -> bool {
+        if self.exclude.is_match(path) {
+            return true;
+        }
+
+        match &self.ignore {
+            // `ls-files` never reports directories, only blob paths, so a
+            // pattern like `target/` only ever matches one of `path`'s
+            // ancestors rather than `path` itself. `matched` alone checks
+            // only `path`; `matched_path_or_any_parents` also walks up
+            // through its parent directories, the way git itself does.
+            Some(ignore) => ignore.matched_path_or_any_parents(path, false).is_ignore(),
+            None => false,
+        }
+    }
+}
+
+/// Builds a real gitignore matcher from `path`, so `!`-negation and
+/// directory anchoring behave the way they would for git itself.
+fn build_gitignore(path: &Path) -> Result<Gitignore, FilterError> {
+    let mut builder = GitignoreBuilder::new(".");
+
+    if let Some(err) = builder.add(path) {
+        return Err(FilterError::InvalidIgnoreFile(path.to_path_buf(), err));
+    }
+
+    builder
+        .build()
+        .map_err(|err| FilterError::InvalidIgnoreFile(path.to_path_buf(), err))
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, FilterError> {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        let glob =
+            Glob::new(pattern).map_err(|err| FilterError::InvalidGlob(pattern.clone(), err))?;
+        builder.add(glob);
+    }
+
+    builder
+        .build()
+        .map_err(|err| FilterError::InvalidGlob(patterns.join(", "), err))
+}
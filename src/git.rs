@@ -0,0 +1,48 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use thiserror::Error;
+
+/// Errors produced by [`git`] while invoking the `git` executable.
+#[derive(Debug, Error)]
+pub enum GitError {
+    #[error("failed to run git {0:?}")]
+    Invoke(String, #[source] io::Error),
+
+    #[error("git {0:?} exited with a non-zero status:\n{1}")]
+    NonZeroExit(String, String),
+
+    #[error("could not decode output of git {0:?} as UTF-8")]
+    DecodeOutput(String),
+}
+
+/// Runs `git <args>` with `cwd` as the working directory and `envs` set
+/// as additional environment variables, returning its captured stdout.
+///
+/// Centralising this avoids scattering `.expect()` calls across the
+/// codebase: callers get a `GitError` they can propagate or report
+/// instead of the process aborting on the first failure.
+pub fn git(args: &[&str], envs: &[(&str, &str)], cwd: &Path) -> Result<String, GitError> {
+    let invocation = args.join(" ");
+
+    let mut command = Command::new("git");
+    command.args(args).current_dir(cwd);
+
+    for (key, value) in envs {
+        command.env(key, value);
+    }
+
+    let output = command
+        .output()
+        .map_err(|err| GitError::Invoke(invocation.clone(), err))?;
+
+    if !output.status.success() {
+        return Err(GitError::NonZeroExit(
+            invocation,
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+
+    String::from_utf8(output.stdout).map_err(|_| GitError::DecodeOutput(invocation))
+}
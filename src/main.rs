@@ -1,11 +1,16 @@
 use clap::Parser;
-use regex::Regex;
-use std::{
-    io::{BufRead, Write},
-    path::{Path, PathBuf},
-    process::Command,
-    sync::LazyLock,
-};
+use std::{io::Write, path::PathBuf};
+
+mod blame;
+mod changes;
+mod filters;
+mod git;
+mod report;
+
+use blame::{blame_for_executable, BlameLine};
+use filters::FileFilter;
+use git::git;
+use report::{LineCountMismatch, LineMismatch, Mismatch};
 
 #[derive(Debug, clap::Parser)]
 #[clap(name = "gix-scripts")]
@@ -27,46 +32,62 @@ pub struct Args {
 
     #[clap(long)]
     pub take: Option<usize>,
-}
 
-static GIT_BLAME_RE: LazyLock<regex::Regex> =
-    LazyLock::new(|| Regex::new(r"\^?([0-9a-f]+) (?:([^(^)]+)\s+)?(\(.* \d+\)) (.*)").unwrap());
+    /// Glob a file must match to be considered; may be given multiple
+    /// times. Defaults to including everything.
+    #[clap(long)]
+    pub include: Vec<String>,
 
-static GIX_BLAME_RE: LazyLock<regex::Regex> =
-    LazyLock::new(|| Regex::new(r"([0-9a-f]+) (\d+) (\d+) (.*)").unwrap());
+    /// Glob that excludes a file even if it matched `--include`; may be
+    /// given multiple times.
+    #[clap(long)]
+    pub exclude: Vec<String>,
 
-fn regex_for_executable(executable: &Path) -> Result<&'static LazyLock<Regex>, ()> {
-    if executable.ends_with("git") {
-        return Ok(&GIT_BLAME_RE);
-    } else if executable.ends_with("gix") {
-        return Ok(&GIX_BLAME_RE);
-    }
+    /// A file of additional exclude patterns, matched with real
+    /// `.gitignore` semantics (negation, directory anchoring, etc.).
+    #[clap(long)]
+    pub ignore_file: Option<PathBuf>,
 
-    Err(())
+    /// Only consider files changed since `<rev>`, i.e. in `<rev>..HEAD`.
+    #[clap(long)]
+    pub since: Option<String>,
+
+    /// Only consider files changed in `<a>..<b>`. Takes precedence over
+    /// `--since`.
+    #[clap(long, value_name = "A..B")]
+    pub commit_range: Option<String>,
+
+    /// Write the full set of line-level mismatches as JSON to this path.
+    #[clap(long)]
+    pub report: Option<PathBuf>,
 }
 
 impl Args {
     fn git_dir(&self) -> PathBuf {
         self.git_work_tree.join(".git")
     }
+
+    fn revision_range(&self) -> Option<String> {
+        self.commit_range
+            .clone()
+            .or_else(|| self.since.as_ref().map(|since| format!("{since}..HEAD")))
+    }
 }
 
-fn main() {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Args = Args::parse_from(std::env::args_os());
 
-    let git_dir = args.git_work_tree.join(".git");
+    let git_dir = args.git_dir();
 
-    let output = Command::new("git")
-        .env("GIT_DIR", git_dir)
-        .args(["ls-files", "--format", "%(path) %(eolinfo:index)"])
-        .output()
-        .expect("failed to run git ls-files");
+    let output = git(
+        &["ls-files", "--format", "%(path) %(eolinfo:index)"],
+        &[("GIT_DIR", &git_dir.to_string_lossy())],
+        &args.git_work_tree,
+    )?;
 
     let filenames: Vec<_> = output
-        .stdout
         .lines()
         .filter_map(|line| {
-            let line = line.expect("could not decode line");
             let parts: Vec<_> = line.split_whitespace().collect();
 
             match parts[..] {
@@ -76,30 +97,67 @@ fn main() {
         })
         .collect();
 
+    let filter = FileFilter::new(&args.include, &args.exclude, args.ignore_file.as_deref())?;
+    let (filenames, filter_counts) = filter.apply(filenames);
+
+    let changed_at = match args.revision_range() {
+        Some(revision_range) => {
+            let changed_at =
+                changes::changed_files_since(&args.git_work_tree, &git_dir, &revision_range)?;
+
+            println!(
+                "restricting to files changed in {revision_range} ({} candidates)",
+                changed_at.len()
+            );
+
+            Some(changed_at)
+        }
+        None => None,
+    };
+
+    let mut filenames: Vec<_> = match &changed_at {
+        Some(changed_at) => filenames
+            .into_iter()
+            .filter(|filename| changed_at.contains_key(filename))
+            .collect(),
+        None => filenames,
+    };
+
+    if let Some(changed_at) = &changed_at {
+        filenames.sort_by_key(|filename| std::cmp::Reverse(changed_at[filename]));
+    }
+
     let number_of_files = filenames.len();
 
     let skip = args.skip.unwrap_or(0);
     let take = args.take.unwrap_or(number_of_files);
 
     println!(
-        "{} files to run blame for, skip {}, take {}",
-        number_of_files, skip, take
+        "{} files to run blame for, skip {}, take {} ({} excluded by --include, {} excluded by --exclude/--ignore-file)",
+        number_of_files,
+        skip,
+        take,
+        filter_counts.excluded_by_include,
+        filter_counts.excluded_by_exclude
     );
     println!("comparing blames");
 
     let mut stdout = std::io::stdout();
 
-    let baseline_regex = regex_for_executable(&args.baseline_executable)
-        .expect("baseline executable is not associated with a regex");
-    let comparison_regex = regex_for_executable(&args.comparison_executable)
-        .expect("comparison executable is not associated with a regex");
+    let baseline_blame = blame_for_executable(&args.baseline_executable);
+    let comparison_blame = blame_for_executable(&args.comparison_executable);
+
+    let filenames_to_process: Vec<_> = filenames.iter().skip(skip).take(take).collect();
 
-    let outcomes: Vec<_> = filenames
+    let outcomes: Vec<_> = filenames_to_process
         .iter()
-        .skip(skip)
-        .take(take)
         .map(|filename| {
-            let result = compare_two_blames(&args, baseline_regex, comparison_regex, filename);
+            let result = compare_two_blames(
+                &args,
+                baseline_blame.as_ref(),
+                comparison_blame.as_ref(),
+                filename,
+            );
 
             let char = match result {
                 Outcome::BlamesMatch => '.',
@@ -115,6 +173,61 @@ fn main() {
 
     println!();
 
+    for (filename, outcome) in filenames_to_process.iter().zip(&outcomes) {
+        if let Outcome::FailedToRunExecutable(message) = outcome {
+            match changed_at
+                .as_ref()
+                .and_then(|changed_at| changed_at.get(*filename))
+            {
+                Some(timestamp) => println!("{filename} (changed at {timestamp}): {message}"),
+                None => println!("{filename}: {message}"),
+            }
+        }
+    }
+
+    if let Some(report_path) = &args.report {
+        let mismatches: Vec<Mismatch> = filenames_to_process
+            .iter()
+            .zip(&outcomes)
+            .filter_map(|(filename, outcome)| {
+                let changed_at = changed_at
+                    .as_ref()
+                    .and_then(|changed_at| changed_at.get(*filename))
+                    .copied();
+
+                match outcome {
+                    Outcome::HashesDidNotMatch(lines) if !lines.is_empty() => Some(Mismatch {
+                        file: (*filename).clone(),
+                        changed_at,
+                        lines: lines.clone(),
+                        line_count_mismatch: None,
+                    }),
+                    Outcome::DifferingLineNumbers {
+                        baseline_line_count,
+                        comparison_line_count,
+                        mismatches,
+                    } => Some(Mismatch {
+                        file: (*filename).clone(),
+                        changed_at,
+                        lines: mismatches.clone(),
+                        line_count_mismatch: Some(LineCountMismatch {
+                            baseline: *baseline_line_count,
+                            comparison: *comparison_line_count,
+                        }),
+                    }),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        println!(
+            "writing {} file mismatch(es) to {}",
+            mismatches.len(),
+            report_path.display()
+        );
+        report::write_report(report_path, &mismatches)?;
+    }
+
     let number_of_matches = outcomes
         .iter()
         .filter(|outcome| matches!(outcome, Outcome::BlamesMatch))
@@ -129,95 +242,84 @@ fn main() {
             number_of_matches, number_of_non_matches
         );
     }
+
+    Ok(())
 }
 
 #[derive(Debug)]
 enum Outcome {
-    DifferingLineNumbers,
+    DifferingLineNumbers {
+        baseline_line_count: usize,
+        comparison_line_count: usize,
+        mismatches: Vec<LineMismatch>,
+    },
     BlamesMatch,
     LineDidNotMatchPattern,
-    HashesDidNotMatch,
-    FailedToRunExecutable,
+    HashesDidNotMatch(Vec<LineMismatch>),
+    FailedToRunExecutable(String),
 }
 
 fn compare_two_blames<T: AsRef<str>>(
     args: &Args,
-    baseline_regex: &LazyLock<Regex>,
-    comparison_regex: &LazyLock<Regex>,
+    baseline_blame: &dyn blame::Blame,
+    comparison_blame: &dyn blame::Blame,
     filename: T,
 ) -> Outcome {
     let extra_args = args.args.clone().unwrap_or("".to_string());
 
-    let baseline_output = Command::new("bash")
-        .env("GIT_DIR", args.git_dir())
-        .env("GIT_WORK_TREE", args.git_work_tree.clone())
-        .arg("-c")
-        .arg(format!(
-            "{} blame {} {}",
-            args.baseline_executable.to_string_lossy(),
-            extra_args,
-            filename.as_ref()
-        ))
-        .output()
-        .expect("failed to run baseline executable");
-
-    if !baseline_output.status.success() {
-        println!("{baseline_output:?}");
-
-        return Outcome::FailedToRunExecutable;
-    }
+    let baseline_lines = match baseline_blame.blame(args, &extra_args, filename.as_ref()) {
+        Ok(lines) => lines,
+        Err(err) => return outcome_for_blame_error(&err),
+    };
 
-    let comparison_output = Command::new("bash")
-        .env("GIT_DIR", args.git_dir())
-        .env("GIT_WORK_TREE", args.git_work_tree.clone())
-        .arg("-c")
-        .arg(format!(
-            "{} blame {} {}",
-            args.comparison_executable.to_string_lossy(),
-            extra_args,
-            filename.as_ref()
-        ))
-        .output()
-        .expect("failed to run comparison executable");
-
-    if !comparison_output.status.success() {
-        println!("{comparison_output:?}");
-
-        return Outcome::FailedToRunExecutable;
-    }
-
-    let baseline_lines: Vec<_> = baseline_output
-        .stdout
-        .lines()
-        .map(|line| line.expect("could not decode line"))
-        .collect();
-    let comparison_lines: Vec<_> = comparison_output
-        .stdout
-        .lines()
-        .map(|line| line.expect("could not decode line"))
-        .collect();
+    let comparison_lines = match comparison_blame.blame(args, &extra_args, filename.as_ref()) {
+        Ok(lines) => lines,
+        Err(err) => return outcome_for_blame_error(&err),
+    };
 
     if baseline_lines.len() != comparison_lines.len() {
-        return Outcome::DifferingLineNumbers;
-    }
-
-    for (baseline_line, comparison_line) in baseline_lines.into_iter().zip(comparison_lines) {
-        let Some(baseline_captures) = baseline_regex.captures(&baseline_line) else {
-            return Outcome::LineDidNotMatchPattern;
-        };
-        let Some(comparison_captures) = comparison_regex.captures(&comparison_line) else {
-            return Outcome::LineDidNotMatchPattern;
+        return Outcome::DifferingLineNumbers {
+            baseline_line_count: baseline_lines.len(),
+            comparison_line_count: comparison_lines.len(),
+            mismatches: mismatches(&baseline_lines, &comparison_lines),
         };
+    }
 
-        let baseline_hash = &baseline_captures[1];
-        let comparison_hash = &comparison_captures[1];
+    let mismatches = mismatches(&baseline_lines, &comparison_lines);
 
-        if !baseline_hash.starts_with(comparison_hash)
-            && !comparison_hash.starts_with(baseline_hash)
-        {
-            return Outcome::HashesDidNotMatch;
-        }
+    if !mismatches.is_empty() {
+        return Outcome::HashesDidNotMatch(mismatches);
     }
 
     Outcome::BlamesMatch
 }
+
+fn hashes_match(baseline: &BlameLine, comparison: &BlameLine) -> bool {
+    baseline.commit_id.starts_with(&comparison.commit_id)
+        || comparison.commit_id.starts_with(&baseline.commit_id)
+}
+
+/// Collects every line on which `baseline` and `comparison` disagree,
+/// zipped up to the shorter of the two so a file with differing line
+/// counts still reports the mismatches found in the overlapping range.
+fn mismatches(baseline: &[BlameLine], comparison: &[BlameLine]) -> Vec<LineMismatch> {
+    baseline
+        .iter()
+        .zip(comparison)
+        .filter(|(baseline_line, comparison_line)| !hashes_match(baseline_line, comparison_line))
+        .map(|(baseline_line, comparison_line)| LineMismatch {
+            line: baseline_line.line_number,
+            baseline_commit_id: baseline_line.commit_id.clone(),
+            baseline_content: baseline_line.content.clone(),
+            comparison_commit_id: comparison_line.commit_id.clone(),
+            comparison_content: comparison_line.content.clone(),
+        })
+        .collect()
+}
+
+fn outcome_for_blame_error(err: &blame::BlameError) -> Outcome {
+    match err {
+        blame::BlameError::LineDidNotMatchPattern(_) => Outcome::LineDidNotMatchPattern,
+        _ => Outcome::FailedToRunExecutable(err.to_string()),
+    }
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+use thiserror::Error;
+
+/// A single line on which the baseline and comparison blames disagreed.
+#[derive(Debug, Clone, Serialize)]
+pub struct LineMismatch {
+    pub line: usize,
+    pub baseline_commit_id: String,
+    pub baseline_content: String,
+    pub comparison_commit_id: String,
+    pub comparison_content: String,
+}
+
+/// The baseline and comparison blame reported a different number of
+/// lines for a file, so their contents couldn't be compared past the
+/// shorter one's length.
+#[derive(Debug, Clone, Serialize)]
+pub struct LineCountMismatch {
+    pub baseline: usize,
+    pub comparison: usize,
+}
+
+/// All the mismatching lines found for one file, plus a record of
+/// whether the two blames disagreed on the file's line count outright.
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    pub file: String,
+    /// The unix timestamp `file` was last changed at, when the run was
+    /// restricted with `--since`/`--commit-range`; `None` otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed_at: Option<i64>,
+    pub lines: Vec<LineMismatch>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_count_mismatch: Option<LineCountMismatch>,
+}
+
+#[derive(Debug, Error)]
+pub enum ReportError {
+    #[error("failed to serialize mismatch report: {0}")]
+    Serialize(#[source] serde_json::Error),
+
+    #[error("failed to write mismatch report to {0:?}: {1}")]
+    Write(std::path::PathBuf, #[source] std::io::Error),
+}
+
+/// Writes `mismatches` to `path` as JSON, so a run's output can be
+/// diffed across runs or fed into CI to catch blame regressions.
+pub fn write_report(path: &Path, mismatches: &[Mismatch]) -> Result<(), ReportError> {
+    let json = serde_json::to_string_pretty(mismatches).map_err(ReportError::Serialize)?;
+
+    fs::write(path, json).map_err(|err| ReportError::Write(path.to_path_buf(), err))
+}